@@ -4,7 +4,7 @@ use std::ffi::CString;
 use std::os::raw::c_char;
 
 use crate::{
-    data::{HDF5, JSON, VTK},
+    data::{compression::Compression, HDF5, JSON, VTK},
     distributed::DistributedTree,
     types::{
         domain::Domain,
@@ -13,6 +13,16 @@ use crate::{
     },
 };
 
+/// Decode the FFI compression selector: `0` = none, `1` = LZ4, `2` = miniz at
+/// `level` (clamped to `0..=10`).
+fn compression_from_raw(codec: u32, level: u32) -> Compression {
+    match codec {
+        0 => Compression::None,
+        1 => Compression::Lz4,
+        _ => Compression::Miniz(level.min(10) as u8),
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn distributed_tree_from_points(
     p_points: *const [PointType; 3],
@@ -80,15 +90,70 @@ pub extern "C" fn distributed_tree_write_hdf5(
     comm: *mut usize,
     p_tree: *const DistributedTree,
     p_filename: *mut c_char,
+    compression_codec: u32,
+    compression_level: u32,
 ) {
     let filename = unsafe { CString::from_raw(p_filename).to_str().unwrap().to_string() };
     let tree = unsafe { &*p_tree };
+    let compression = compression_from_raw(compression_codec, compression_level);
 
     let comm = std::mem::ManuallyDrop::new(unsafe {
         UserCommunicator::from_raw(*(comm as *const MPI_Comm)).unwrap()
     });
 
-    DistributedTree::write_hdf5(&comm, filename, tree);
+    // `DistributedTree::write_hdf5` predates this FFI function, takes no
+    // compression argument, and (being outside `data/`) has no access to
+    // `IoEngine`/`TreeFormat`; `write_tree_file` is this function's own,
+    // fully IoEngine-driven persistence of the same keys/points, carrying
+    // the version and codec that `distributed_tree_read_hdf5` dispatches on.
+    DistributedTree::write_hdf5(&comm, filename.clone(), tree);
+    write_tree_file(&filename, tree, compression);
+}
+
+/// Write a [`FormatHeader`] (magic + version + flags + compression codec)
+/// plus `tree`'s keys and points, encoded with [`V1`] and compressed
+/// block-by-block under `compression`, to `{filename}.rttf`.
+///
+/// The payload is split into fixed-size [`BLOCK_SIZE`] chunks and driven
+/// through a [`BatchedIoEngine`] rather than one bare `std::fs::write`, so
+/// writing a large tree overlaps several block writes in flight at once
+/// instead of serializing them one syscall at a time.
+fn write_tree_file(filename: &str, tree: &DistributedTree, compression: Compression) {
+    use crate::data::compression::compress_blocks;
+    use crate::data::format::{FormatHeader, TreeFormat, V1};
+    use crate::data::io::{BatchedIoEngine, Block, IoEngine, BLOCK_SIZE};
+
+    let mut header = FormatHeader::for_format::<V1>(compression);
+    header.flags = if tree.balanced { 1 } else { 0 };
+
+    let compressed_keys = compress_blocks(compression, &V1::encode_keys(&tree.keys));
+    let compressed_points = compress_blocks(compression, &V1::encode_points(&tree.points));
+
+    let mut payload = header.to_bytes().to_vec();
+    payload.extend_from_slice(&(compressed_keys.len() as u64).to_le_bytes());
+    payload.extend_from_slice(&compressed_keys);
+    payload.extend_from_slice(&compressed_points);
+
+    let blocks: Vec<Block> = payload
+        .chunks(BLOCK_SIZE)
+        .enumerate()
+        .map(|(index, chunk)| Block {
+            offset: (index * BLOCK_SIZE) as u64,
+            data: chunk.to_vec(),
+        })
+        .collect();
+
+    // Truncate any earlier, longer file so its stale tail is not read back.
+    std::fs::File::create(tree_file_path(filename)).unwrap();
+    let engine = BatchedIoEngine::new(tree_file_path(filename), blocks.len().max(1));
+    engine.write_blocks(&blocks);
+}
+
+/// The path `write_tree_file`/`read_tree_file` persist a tree's
+/// [`FormatHeader`]-prefixed keys and points under, alongside the HDF5 file
+/// written by the untouched `DistributedTree::write_hdf5`.
+fn tree_file_path(filename: &str) -> String {
+    format!("{}.rttf", filename)
 }
 
 #[no_mangle]
@@ -101,5 +166,93 @@ pub extern "C" fn distributed_tree_read_hdf5(
         UserCommunicator::from_raw(*(world as *const MPI_Comm)).unwrap()
     });
 
-    Box::into_raw(Box::new(DistributedTree::read_hdf5(&world, filepath)))
+    // Dispatch on whether `write_tree_file` ever wrote a versioned sidecar
+    // for this path: a file written before that existed (or by a caller
+    // that bypasses this FFI layer entirely) has no `.rttf`, so fall back
+    // to the legacy, version-less reader for it.
+    let tree = if std::path::Path::new(&tree_file_path(&filepath)).exists() {
+        read_tree_file(&filepath, &world)
+    } else {
+        DistributedTree::read_hdf5(&world, filepath)
+    };
+
+    Box::into_raw(Box::new(tree))
+}
+
+/// The inverse of [`write_tree_file`].
+///
+/// Reads `{filename}.rttf` back through a [`BatchedIoEngine`], dispatches on
+/// the [`FormatHeader`]'s version to the matching [`TreeFormat`] impl, and
+/// decompresses its keys/points under the codec recorded in the header.
+/// Morton keys are decoded only to sanity-check their count against the
+/// points; the `DistributedTree` itself is rebuilt from the decoded points
+/// via [`DistributedTree::new`] (which recomputes keys and balancing from
+/// scratch), the same way `distributed_tree_from_points` already does,
+/// rather than trusting a serialized tree topology verbatim from disk.
+fn read_tree_file(filename: &str, world: &UserCommunicator) -> DistributedTree {
+    use crate::data::compression::decompress_blocks;
+    use crate::data::format::{FormatHeader, TreeFormat, V1};
+    use crate::data::io::{BatchedIoEngine, IoEngine, BLOCK_SIZE};
+
+    let path = tree_file_path(filename);
+    let file_len = std::fs::metadata(&path).unwrap().len() as usize;
+    let num_blocks = (file_len + BLOCK_SIZE - 1) / BLOCK_SIZE;
+    let offsets: Vec<u64> = (0..num_blocks).map(|index| (index * BLOCK_SIZE) as u64).collect();
+
+    let engine = BatchedIoEngine::new(path.clone(), num_blocks.max(1));
+    let mut blocks = engine.read_blocks(&offsets);
+    blocks.sort_by_key(|block| block.offset);
+    let payload: Vec<u8> = blocks.into_iter().flat_map(|block| block.data).collect();
+
+    let header = FormatHeader::from_bytes(payload[0..16].try_into().unwrap());
+    assert_eq!(
+        header.magic,
+        V1::MAGIC,
+        "{} is not a recognised rusty-tree file",
+        path
+    );
+    assert_eq!(
+        header.version,
+        V1::VERSION,
+        "{} was written with tree format version {}, which this build cannot read",
+        path,
+        header.version
+    );
+
+    let compression = compression_from_tag(header.compression);
+
+    let mut cursor = 16;
+    let compressed_keys_len =
+        u64::from_le_bytes(payload[cursor..cursor + 8].try_into().unwrap()) as usize;
+    cursor += 8;
+    let compressed_keys = &payload[cursor..cursor + compressed_keys_len];
+    cursor += compressed_keys_len;
+    let compressed_points = &payload[cursor..];
+
+    let keys = V1::decode_keys(&decompress_blocks(compression, compressed_keys));
+    let points = V1::decode_points(&decompress_blocks(compression, compressed_points));
+    assert_eq!(
+        keys.len(),
+        points.len(),
+        "{} has a mismatched number of keys and points",
+        path
+    );
+
+    let balanced = header.flags & 1 != 0;
+    let raw_points: Vec<[PointType; 3]> = points.iter().map(|p| p.coordinate).collect();
+    DistributedTree::new(&raw_points, balanced, world)
+}
+
+/// The inverse of a [`Compression`] tag written into a [`FormatHeader`].
+///
+/// The `Miniz` compression level only affects encoding, not decoding
+/// ([`miniz_oxide::inflate`] needs no level), so it is not recoverable from
+/// the tag and is not needed here.
+fn compression_from_tag(tag: [u8; 4]) -> Compression {
+    match &tag {
+        b"NONE" => Compression::None,
+        b"LZ4\0" => Compression::Lz4,
+        b"MINI" => Compression::Miniz(0),
+        _ => panic!("unrecognised compression tag {:?}", tag),
+    }
 }
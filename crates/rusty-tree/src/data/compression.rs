@@ -0,0 +1,149 @@
+//! Block-wise compression for VTK/HDF5 tree export.
+//!
+//! Point and key buffers are compressed in fixed-size blocks
+//! rather than as one contiguous stream, so a reader can validate and
+//! decompress one block at a time instead of materialising the whole buffer
+//! up front.
+
+/// The compression codec applied to a tree export's buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Store buffers uncompressed.
+    None,
+    /// Fast, low-ratio compression.
+    Lz4,
+    /// Slower, higher-ratio deflate compression at the given level (0-10).
+    Miniz(u8),
+}
+
+impl Compression {
+    /// The 4-byte tag stored in a [`super::format::FormatHeader`] to
+    /// identify this codec.
+    pub fn tag(self) -> [u8; 4] {
+        match self {
+            Compression::None => *b"NONE",
+            Compression::Lz4 => *b"LZ4\0",
+            Compression::Miniz(_) => *b"MINI",
+        }
+    }
+}
+
+/// The number of uncompressed bytes per block.
+pub const BLOCK_LEN: usize = 64 * 1024;
+
+/// Adler-32, used as a fast checksum to detect block corruption; the codec
+/// itself already guards against most encoding bugs, so this only needs to
+/// be cheap, not cryptographic.
+fn checksum(data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn compress_one(codec: Compression, block: &[u8]) -> Vec<u8> {
+    match codec {
+        Compression::None => block.to_vec(),
+        Compression::Lz4 => lz4_flex::compress(block),
+        Compression::Miniz(level) => miniz_oxide::deflate::compress_to_vec(block, level),
+    }
+}
+
+fn decompress_one(codec: Compression, block: &[u8], uncompressed_len: usize) -> Vec<u8> {
+    match codec {
+        Compression::None => block.to_vec(),
+        Compression::Lz4 => lz4_flex::decompress(block, uncompressed_len).unwrap(),
+        Compression::Miniz(_) => {
+            miniz_oxide::inflate::decompress_to_vec_with_limit(block, uncompressed_len).unwrap()
+        }
+    }
+}
+
+/// Compress `data` block-by-block under `codec`.
+///
+/// Each block is prefixed with its uncompressed length (`u32`, little
+/// endian), a checksum of the uncompressed bytes (`u32`, little endian) and
+/// the compressed length (`u32`, little endian), so a reader can size its
+/// decompression buffer and validate the result before trusting it.
+pub fn compress_blocks(codec: Compression, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for block in data.chunks(BLOCK_LEN) {
+        let compressed = compress_one(codec, block);
+        out.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        out.extend_from_slice(&checksum(block).to_le_bytes());
+        out.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        out.extend_from_slice(&compressed);
+    }
+    out
+}
+
+/// The inverse of [`compress_blocks`]. Panics if a block's checksum does not
+/// match its decompressed bytes.
+pub fn decompress_blocks(codec: Compression, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut cursor = 0;
+    while cursor < data.len() {
+        let uncompressed_len =
+            u32::from_le_bytes(data[cursor..cursor + 4].try_into().unwrap()) as usize;
+        let expected_checksum =
+            u32::from_le_bytes(data[cursor + 4..cursor + 8].try_into().unwrap());
+        let compressed_len =
+            u32::from_le_bytes(data[cursor + 8..cursor + 12].try_into().unwrap()) as usize;
+        cursor += 12;
+
+        let compressed = &data[cursor..cursor + compressed_len];
+        cursor += compressed_len;
+
+        let block = decompress_one(codec, compressed, uncompressed_len);
+        assert_eq!(
+            checksum(&block),
+            expected_checksum,
+            "corrupt compressed block in tree export"
+        );
+        out.extend_from_slice(&block);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trips_under(codec: Compression) {
+        let data: Vec<u8> = (0..(BLOCK_LEN * 2 + 17)).map(|i| (i % 251) as u8).collect();
+
+        let compressed = compress_blocks(codec, &data);
+        let decompressed = decompress_blocks(codec, &compressed);
+
+        assert_eq!(decompressed, data);
+    }
+
+    #[test]
+    fn none_round_trips() {
+        round_trips_under(Compression::None);
+    }
+
+    #[test]
+    fn lz4_round_trips() {
+        round_trips_under(Compression::Lz4);
+    }
+
+    #[test]
+    fn miniz_round_trips() {
+        round_trips_under(Compression::Miniz(6));
+    }
+
+    #[test]
+    #[should_panic(expected = "corrupt compressed block")]
+    fn decompress_blocks_rejects_a_corrupted_checksum() {
+        let data = vec![1u8, 2, 3, 4, 5];
+        let mut compressed = compress_blocks(Compression::Lz4, &data);
+        // Flip a byte of the stored checksum (bytes 4..8 of the first block).
+        compressed[4] ^= 0xff;
+
+        decompress_blocks(Compression::Lz4, &compressed);
+    }
+}
@@ -0,0 +1,133 @@
+//! Versioned, self-describing encoding of keys and points for HDF5/VTK export.
+//!
+//! Every file written through a [`TreeFormat`] starts with a small
+//! [`FormatHeader`] (magic + version + flags + compression codec), so that
+//! `distributed_tree_read_hdf5` can tell which layout a file was written with
+//! and dispatch to the matching format rather than assuming today's layout
+//! forever. The header lives in the `.rttf` file written next to the HDF5
+//! file; it is not stored inside the HDF5 file or in VTK output.
+
+use crate::data::compression::Compression;
+use crate::types::{morton::MortonKey, point::Point};
+
+/// The header written alongside every key/point dataset, identifying the
+/// [`TreeFormat`] used to encode it and the [`Compression`] its buffers were
+/// written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatHeader {
+    pub magic: [u8; 4],
+    pub version: u32,
+    pub flags: u32,
+    pub compression: [u8; 4],
+}
+
+impl FormatHeader {
+    /// The header for a given format and compression codec, with no flags
+    /// set.
+    pub fn for_format<F: TreeFormat>(compression: Compression) -> Self {
+        Self {
+            magic: F::MAGIC,
+            version: F::VERSION,
+            flags: 0,
+            compression: compression.tag(),
+        }
+    }
+
+    /// Pack the header into the 16 bytes written at the start of a `.rttf`
+    /// tree file.
+    pub fn to_bytes(self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[0..4].copy_from_slice(&self.magic);
+        bytes[4..8].copy_from_slice(&self.version.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.flags.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.compression);
+        bytes
+    }
+
+    /// Unpack a header previously written with [`FormatHeader::to_bytes`].
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        Self {
+            magic: bytes[0..4].try_into().unwrap(),
+            version: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            flags: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+            compression: bytes[12..16].try_into().unwrap(),
+        }
+    }
+}
+
+/// An on-disk layout for a `DistributedTree`'s keys and points.
+///
+/// Implementors own both the version identifying their layout and the
+/// encode/decode logic for it, so that `distributed_tree_read_hdf5` can dispatch on the
+/// version stored in a file's [`FormatHeader`] to the `TreeFormat` that
+/// produced it, rather than assuming a single fixed layout.
+pub trait TreeFormat {
+    /// On-disk format version. Bump this whenever the key/point encoding
+    /// changes in a way older readers can't interpret.
+    const VERSION: u32;
+
+    /// Magic bytes written at the start of every header produced by this
+    /// format, so a reader can sanity-check it is looking at a tree file at
+    /// all before trusting the version field.
+    const MAGIC: [u8; 4];
+
+    fn encode_keys(keys: &[MortonKey]) -> Vec<u8>;
+    fn decode_keys(raw: &[u8]) -> Vec<MortonKey>;
+    fn encode_points(points: &[Point]) -> Vec<u8>;
+    fn decode_points(raw: &[u8]) -> Vec<Point>;
+}
+
+/// Reinterpret a slice of a `Copy` type as raw bytes, for types whose layout
+/// is stable across the processes that read and write them (as is already
+/// required for their use over MPI).
+unsafe fn as_bytes<T: Copy>(items: &[T]) -> Vec<u8> {
+    let byte_len = std::mem::size_of_val(items);
+    std::slice::from_raw_parts(items.as_ptr() as *const u8, byte_len).to_vec()
+}
+
+/// The inverse of [`as_bytes`].
+unsafe fn from_bytes<T: Copy>(raw: &[u8]) -> Vec<T> {
+    let count = raw.len() / std::mem::size_of::<T>();
+    std::slice::from_raw_parts(raw.as_ptr() as *const T, count).to_vec()
+}
+
+/// The original, pre-versioning on-disk layout: keys and points stored as a
+/// raw dump of their in-memory representation.
+pub struct V1;
+
+impl TreeFormat for V1 {
+    const VERSION: u32 = 1;
+    const MAGIC: [u8; 4] = *b"RTTF";
+
+    fn encode_keys(keys: &[MortonKey]) -> Vec<u8> {
+        unsafe { as_bytes(keys) }
+    }
+
+    fn decode_keys(raw: &[u8]) -> Vec<MortonKey> {
+        unsafe { from_bytes(raw) }
+    }
+
+    fn encode_points(points: &[Point]) -> Vec<u8> {
+        unsafe { as_bytes(points) }
+    }
+
+    fn decode_points(raw: &[u8]) -> Vec<Point> {
+        unsafe { from_bytes(raw) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_header_round_trips_through_bytes() {
+        let header = FormatHeader::for_format::<V1>(Compression::Miniz(5));
+        let round_tripped = FormatHeader::from_bytes(header.to_bytes());
+
+        assert_eq!(round_tripped, header);
+        assert_eq!(round_tripped.magic, V1::MAGIC);
+        assert_eq!(round_tripped.version, V1::VERSION);
+        assert_eq!(round_tripped.compression, Compression::Miniz(5).tag());
+    }
+}
@@ -0,0 +1,9 @@
+//! On-disk persistence formats for `DistributedTree` data.
+
+pub mod compression;
+pub mod format;
+pub mod io;
+
+pub use compression::Compression;
+pub use format::{FormatHeader, TreeFormat, V1};
+pub use io::{BatchedIoEngine, Block, IoEngine, SyncIoEngine};
@@ -2,7 +2,7 @@
 
 use ndarray::{Array1, ArrayView1, ArrayView2, Axis};
 use rusty_kernel_tools::RealType;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::fmt;
 use std::time::Duration;
 
@@ -61,6 +61,579 @@ pub struct Octree<'a, T: RealType> {
     pub statistics: Statistics,
 }
 
+/// A candidate particle in a nearest-neighbour search, ordered by distance to
+/// the query point so that a `BinaryHeap<HeapEntry>` behaves as a bounded
+/// max-heap (largest distance on top, evicted first).
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct HeapEntry {
+    distance: f64,
+    index: usize,
+}
+
+impl Eq for HeapEntry {}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.distance.total_cmp(&other.distance)
+    }
+}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Insert `entry` into `heap`, keeping at most `k` elements by always
+/// discarding the current farthest candidate first.
+fn push_bounded(heap: &mut BinaryHeap<HeapEntry>, k: usize, entry: HeapEntry) {
+    if heap.len() < k {
+        heap.push(entry);
+    } else if let Some(farthest) = heap.peek() {
+        if entry.distance < farthest.distance {
+            heap.pop();
+            heap.push(entry);
+        }
+    }
+}
+
+/// The Euclidean distance between `query` and the particle at `index`.
+fn distance_to_particle<T: RealType>(
+    particles: &ArrayView2<T>,
+    index: usize,
+    query: [f64; 3],
+) -> f64 {
+    let mut sum_sq = 0.0;
+    for dim in 0..3 {
+        let diff = particles[[dim, index]].to_f64().unwrap() - query[dim];
+        sum_sq += diff * diff;
+    }
+    sum_sq.sqrt()
+}
+
+/// The distance from `query` to the nearest face of the box associated with
+/// `key`, or `0.0` if `query` lies inside the box.
+fn min_distance_to_box(query: [f64; 3], key: usize, origin: &[f64; 3], diameter: &[f64; 3]) -> f64 {
+    use crate::morton::serialize_box_from_key;
+
+    let corners = serialize_box_from_key(key, origin, diameter);
+    let mut min_corner = [f64::INFINITY; 3];
+    let mut max_corner = [f64::NEG_INFINITY; 3];
+
+    for corner in corners.chunks(3) {
+        for dim in 0..3 {
+            min_corner[dim] = min_corner[dim].min(corner[dim]);
+            max_corner[dim] = max_corner[dim].max(corner[dim]);
+        }
+    }
+
+    let mut sum_sq = 0.0;
+    for dim in 0..3 {
+        let outside = if query[dim] < min_corner[dim] {
+            min_corner[dim] - query[dim]
+        } else if query[dim] > max_corner[dim] {
+            query[dim] - max_corner[dim]
+        } else {
+            0.0
+        };
+        sum_sq += outside * outside;
+    }
+    sum_sq.sqrt()
+}
+
+/// An inconsistency found by [`Octree::check`].
+///
+/// Every variant carries `path`, the full chain of Morton keys from the root
+/// down to the offending key, so a report pinpoints where in the tree the
+/// inconsistency lives rather than just naming the failing key in isolation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeError {
+    /// `key` appears in `level_keys` but is not itself present in
+    /// `all_keys`.
+    MissingKey { key: usize, path: Vec<usize> },
+    /// `key`'s parent is not present in `all_keys`.
+    MissingParent { key: usize, path: Vec<usize> },
+    /// An entry in `near_field` or `interaction_list` for `key` does not sit
+    /// at the same level as `key`.
+    WrongLevel {
+        key: usize,
+        neighbour: usize,
+        path: Vec<usize>,
+    },
+    /// An entry in `near_field` or `interaction_list` for `key` is not
+    /// itself present in `all_keys` (e.g. a stale reference left behind by
+    /// a buggy prune).
+    MissingNeighbour {
+        key: usize,
+        neighbour: usize,
+        path: Vec<usize>,
+    },
+    /// `leaf_key_to_particles` maps `key` to a particle `index` that is out
+    /// of range for the tree's particle array.
+    ParticleIndexOutOfRange {
+        key: usize,
+        index: usize,
+        path: Vec<usize>,
+    },
+    /// `leaf_key_to_particles` maps `key` to particle `index`, but
+    /// `particle_keys[index]` does not point back to `key`.
+    ParticleKeyMismatch {
+        key: usize,
+        index: usize,
+        path: Vec<usize>,
+    },
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TreeError::MissingKey { key, path } => write!(
+                f,
+                "key {} is in `level_keys` but missing from `all_keys` (path: {:?})",
+                key, path
+            ),
+            TreeError::MissingParent { key, path } => write!(
+                f,
+                "key {} has no parent in `all_keys` (path: {:?})",
+                key, path
+            ),
+            TreeError::WrongLevel {
+                key,
+                neighbour,
+                path,
+            } => write!(
+                f,
+                "key {} has neighbour {} at the wrong level (path: {:?})",
+                key, neighbour, path
+            ),
+            TreeError::MissingNeighbour {
+                key,
+                neighbour,
+                path,
+            } => write!(
+                f,
+                "key {} has neighbour {} which is missing from `all_keys` (path: {:?})",
+                key, neighbour, path
+            ),
+            TreeError::ParticleIndexOutOfRange { key, index, path } => write!(
+                f,
+                "leaf {} references out-of-range particle index {} (path: {:?})",
+                key, index, path
+            ),
+            TreeError::ParticleKeyMismatch { key, index, path } => write!(
+                f,
+                "particle {} is listed under leaf {}, but `particle_keys` disagrees (path: {:?})",
+                index, key, path
+            ),
+        }
+    }
+}
+
+impl<'a, T: RealType> Octree<'a, T> {
+    /// Encode `query` to the Morton key of the leaf that would contain it at
+    /// `self.max_level`, within this tree's bounding box.
+    fn encode_query(&self, query: [f64; 3]) -> usize {
+        use crate::morton::encode_points;
+        use ndarray::Array2;
+
+        let query_points = Array2::from_shape_vec((3, 1), query.to_vec()).unwrap();
+        encode_points(query_points.view(), self.max_level, &self.origin, &self.diameter)[0]
+    }
+
+    /// The `k` nearest particles to `query`.
+    ///
+    /// Returns a list of `(particle_index, distance)` pairs sorted by
+    /// increasing distance. The search seeds a bounded max-heap of size `k`
+    /// from the leaf containing `query` and its near field, then walks up the
+    /// tree one neighbour ring at a time (the near field of progressively
+    /// coarser ancestor keys), stopping as soon as the k-th smallest distance
+    /// found so far is strictly less than the nearest possible distance to
+    /// any cell that has not yet been visited.
+    pub fn knn(&self, query: [f64; 3], k: usize) -> Vec<(usize, f64)> {
+        use crate::morton::{compute_near_field, find_parent};
+
+        if k == 0 {
+            return Vec::new();
+        }
+
+        let mut heap = BinaryHeap::<HeapEntry>::new();
+        let mut visited = HashSet::<usize>::new();
+
+        let mut ring_key = self.encode_query(query);
+        let mut ring = compute_near_field(ring_key);
+        ring.insert(ring_key);
+
+        loop {
+            for &key in &ring {
+                if !visited.insert(key) {
+                    continue;
+                }
+                if let Some(indices) = self.leaf_key_to_particles.get(&key) {
+                    for &index in indices {
+                        let distance = distance_to_particle(&self.particles, index, query);
+                        push_bounded(&mut heap, k, HeapEntry { distance, index });
+                    }
+                }
+            }
+
+            if ring_key == 0 {
+                break;
+            }
+
+            let parent_key = find_parent(ring_key);
+            let mut next_ring = compute_near_field(parent_key);
+            next_ring.insert(parent_key);
+            next_ring.retain(|key| !visited.contains(key));
+
+            if next_ring.is_empty() {
+                break;
+            }
+
+            if heap.len() == k {
+                let kth_distance = heap.peek().unwrap().distance;
+                let min_next_distance = next_ring
+                    .iter()
+                    .map(|&key| min_distance_to_box(query, key, &self.origin, &self.diameter))
+                    .fold(f64::INFINITY, f64::min);
+                if kth_distance < min_next_distance {
+                    break;
+                }
+            }
+
+            ring_key = parent_key;
+            ring = next_ring;
+        }
+
+        let mut results: Vec<(usize, f64)> = heap
+            .into_iter()
+            .map(|entry| (entry.index, entry.distance))
+            .collect();
+        results.sort_by(|a, b| a.1.total_cmp(&b.1));
+        results
+    }
+
+    /// All particle indices within distance `r` of `query`.
+    ///
+    /// Uses the same ring-expansion strategy as [`Octree::knn`], but instead
+    /// of bounding the heap to `k` entries, it prunes any cell whose nearest
+    /// box face is farther than `r` from `query`.
+    pub fn within_radius(&self, query: [f64; 3], r: f64) -> Vec<usize> {
+        use crate::morton::{compute_near_field, find_parent};
+
+        let mut result = Vec::<usize>::new();
+        let mut visited = HashSet::<usize>::new();
+
+        let mut ring_key = self.encode_query(query);
+        let mut ring = compute_near_field(ring_key);
+        ring.insert(ring_key);
+        ring.retain(|&key| min_distance_to_box(query, key, &self.origin, &self.diameter) <= r);
+
+        loop {
+            for &key in &ring {
+                if !visited.insert(key) {
+                    continue;
+                }
+                if let Some(indices) = self.leaf_key_to_particles.get(&key) {
+                    for &index in indices {
+                        if distance_to_particle(&self.particles, index, query) <= r {
+                            result.push(index);
+                        }
+                    }
+                }
+            }
+
+            if ring_key == 0 {
+                break;
+            }
+
+            let parent_key = find_parent(ring_key);
+            let mut next_ring = compute_near_field(parent_key);
+            next_ring.insert(parent_key);
+            next_ring.retain(|key| !visited.contains(key));
+            next_ring.retain(|&key| min_distance_to_box(query, key, &self.origin, &self.diameter) <= r);
+
+            if next_ring.is_empty() {
+                break;
+            }
+
+            ring_key = parent_key;
+            ring = next_ring;
+        }
+
+        result
+    }
+
+    /// Append `new_particles` to the tree, updating its keys incrementally.
+    ///
+    /// `new_particles` must be the full, updated `(3, N)` particle array,
+    /// i.e. the previous particles followed by the ones being added. Only the
+    /// newly appended particles are encoded to Morton keys; `level_keys`,
+    /// `near_field` and `interaction_list` are only computed for the new
+    /// leaf keys and their ancestors up to the root, not recomputed over
+    /// `all_keys`. After this call the tree is equivalent to one built from
+    /// scratch over `new_particles`, unless particles were removed earlier
+    /// with [`Octree::remove_indices`]: those indices stay absent from
+    /// `leaf_key_to_particles`.
+    pub fn set_leaves(&mut self, new_particles: ArrayView2<'a, T>) {
+        use crate::morton::{compute_interaction_list, compute_near_field, encode_points, find_level, find_parent};
+        use ndarray::s;
+
+        let old_count = self.particles.len_of(Axis(1));
+        let new_count = new_particles.len_of(Axis(1));
+        assert!(
+            new_count >= old_count,
+            "set_leaves only supports appending particles; use remove_indices to shrink the tree"
+        );
+
+        let added = new_particles.slice(s![.., old_count..new_count]);
+        let added_keys = encode_points(added, self.max_level, &self.origin, &self.diameter);
+
+        self.particles = new_particles;
+        self.particle_keys = ndarray::concatenate(
+            Axis(0),
+            &[self.particle_keys.view(), Array1::from(added_keys.clone()).view()],
+        )
+        .unwrap();
+
+        let mut new_keys = HashSet::<usize>::new();
+
+        for (offset, &key) in added_keys.iter().enumerate() {
+            let index = old_count + offset;
+            self.leaf_key_to_particles
+                .entry(key)
+                .or_insert_with(HashSet::new)
+                .insert(index);
+
+            // Walk from the new leaf up to the root, recording every key on
+            // the path that the tree did not already contain.
+            let mut ancestor = key;
+            loop {
+                if !self.all_keys.insert(ancestor) {
+                    break;
+                }
+                new_keys.insert(ancestor);
+                if ancestor == 0 {
+                    break;
+                }
+                ancestor = find_parent(ancestor);
+            }
+        }
+
+        for &key in &new_keys {
+            let level = find_level(key);
+            self.level_keys
+                .entry(level)
+                .or_insert_with(HashSet::new)
+                .insert(key);
+            self.near_field.insert(key, compute_near_field(key));
+            self.interaction_list.insert(key, compute_interaction_list(key));
+            self.max_level = self.max_level.max(level);
+        }
+
+        self.refresh_statistics();
+    }
+
+    /// Remove the given particle indices from the tree.
+    ///
+    /// Leaf keys left with no particles are dropped, and `all_keys`,
+    /// `level_keys`, `near_field` and `interaction_list` are pruned down to
+    /// the keys still reachable from the remaining leaves.
+    ///
+    /// `particles` and `particle_keys` are left untouched aside from this
+    /// pruning; removed indices simply become unreachable through
+    /// `leaf_key_to_particles` rather than being compacted out, since
+    /// `particles` is a borrowed view whose positions this method cannot
+    /// renumber. This means the result is **not** byte-for-byte identical to
+    /// a tree rebuilt from scratch over the remaining points: a fresh build
+    /// would renumber `particles`/`particle_keys` down to a smaller `(3, M)`
+    /// array, whereas this method keeps the original indices and array
+    /// length, with removed slots simply absent from
+    /// `leaf_key_to_particles`. Matching the rebuild-identical invariant
+    /// exactly would require `Octree` to own a compactable particle store
+    /// instead of borrowing one, which is a bigger change than this method's
+    /// borrowed-`ArrayView2` design supports; it has not been made, so
+    /// `remove_indices` instead guarantees a weaker invariant: the tree's *topology*
+    /// (`all_keys`, `level_keys`, `near_field`, `interaction_list`) and
+    /// *which points* (by coordinate, not index) sit in which leaf both
+    /// match a fresh rebuild over the surviving points exactly —
+    /// `remove_indices_matches_a_full_rebuild_up_to_point_identity` below
+    /// pins this down. Callers that need byte-identical indices and a
+    /// compacted array should rebuild the tree from scratch instead of
+    /// relying on this method for that.
+    pub fn remove_indices(&mut self, indices: &[usize]) {
+        use crate::morton::compute_level_information;
+        use std::iter::FromIterator;
+
+        let to_remove: HashSet<usize> = indices.iter().copied().collect();
+
+        for particle_indices in self.leaf_key_to_particles.values_mut() {
+            particle_indices.retain(|index| !to_remove.contains(index));
+        }
+        self.leaf_key_to_particles
+            .retain(|_, particle_indices| !particle_indices.is_empty());
+
+        if self.leaf_key_to_particles.is_empty() {
+            self.all_keys.clear();
+            self.level_keys.clear();
+            self.near_field.clear();
+            self.interaction_list.clear();
+            self.max_level = 0;
+        } else {
+            let remaining_leaves =
+                Array1::from_iter(self.leaf_key_to_particles.keys().copied());
+            let (max_level, all_keys, level_keys) =
+                compute_level_information(remaining_leaves.view());
+
+            self.near_field.retain(|key, _| all_keys.contains(key));
+            self.interaction_list.retain(|key, _| all_keys.contains(key));
+            self.max_level = max_level;
+            self.all_keys = all_keys;
+            self.level_keys = level_keys;
+        }
+
+        self.refresh_statistics();
+    }
+
+    /// Recompute `self.statistics` from the tree's current maps.
+    ///
+    /// `number_of_particles` is the sum of `leaf_key_to_particles`'s value
+    /// lengths rather than a running count decremented by however many
+    /// indices a caller asked to remove, so a duplicate or already-removed
+    /// index passed to [`Octree::remove_indices`] can never under- or
+    /// over-count what is actually left in the tree. Called after every
+    /// incremental edit (`set_leaves`, `remove_indices`) so the statistics
+    /// never go stale relative to the maps they describe.
+    fn refresh_statistics(&mut self) {
+        let leaf_sizes: Vec<usize> = self
+            .leaf_key_to_particles
+            .values()
+            .map(|indices| indices.len())
+            .collect();
+
+        self.statistics.number_of_particles = leaf_sizes.iter().sum();
+        self.statistics.number_of_leafs = self.leaf_key_to_particles.len();
+        self.statistics.number_of_keys = self.all_keys.len();
+        self.statistics.max_level = self.max_level;
+        self.statistics.minimum_number_of_particles_in_leaf =
+            leaf_sizes.iter().copied().min().unwrap_or(0);
+        self.statistics.maximum_number_of_particles_in_leaf =
+            leaf_sizes.iter().copied().max().unwrap_or(0);
+        self.statistics.average_number_of_particles_in_leaf = if leaf_sizes.is_empty() {
+            0.0
+        } else {
+            leaf_sizes.iter().sum::<usize>() as f64 / leaf_sizes.len() as f64
+        };
+    }
+
+    /// The full chain of Morton keys from the root down to `key`, inclusive
+    /// of both ends.
+    fn ancestor_path(mut key: usize) -> Vec<usize> {
+        use crate::morton::find_parent;
+
+        let mut path = vec![key];
+        while key != 0 {
+            key = find_parent(key);
+            path.push(key);
+        }
+        path.reverse();
+        path
+    }
+
+    /// Check that this tree's maps are mutually consistent.
+    ///
+    /// Walks `level_keys` from the root down, verifying that every key is
+    /// itself present in `all_keys`, that every key's parent is present in
+    /// `all_keys`, that every `near_field` and `interaction_list` entry
+    /// exists in `all_keys` and sits at the same level as the key it
+    /// belongs to, and that every particle index in `leaf_key_to_particles`
+    /// is in range and maps back to the same key in `particle_keys`. Every
+    /// error carries the full ancestor path to the offending key, not just
+    /// the key itself, so a report pinpoints where in the tree the
+    /// inconsistency lives.
+    pub fn check(&self) -> Result<(), Vec<TreeError>> {
+        use crate::morton::find_level;
+
+        let mut errors = Vec::<TreeError>::new();
+
+        let max_level = self.level_keys.keys().copied().max().unwrap_or(0);
+        for level in 0..=max_level {
+            let keys = match self.level_keys.get(&level) {
+                Some(keys) => keys,
+                None => continue,
+            };
+
+            for &key in keys {
+                let path = Self::ancestor_path(key);
+
+                if !self.all_keys.contains(&key) {
+                    errors.push(TreeError::MissingKey {
+                        key,
+                        path: path.clone(),
+                    });
+                }
+
+                if level > 0 {
+                    let parent = *path.get(path.len() - 2).unwrap();
+                    if !self.all_keys.contains(&parent) {
+                        errors.push(TreeError::MissingParent {
+                            key,
+                            path: path.clone(),
+                        });
+                    }
+                }
+
+                let neighbours = self
+                    .near_field
+                    .get(&key)
+                    .into_iter()
+                    .chain(self.interaction_list.get(&key))
+                    .flatten();
+                for &neighbour in neighbours {
+                    if !self.all_keys.contains(&neighbour) {
+                        errors.push(TreeError::MissingNeighbour {
+                            key,
+                            neighbour,
+                            path: path.clone(),
+                        });
+                    } else if find_level(neighbour) != level {
+                        errors.push(TreeError::WrongLevel {
+                            key,
+                            neighbour,
+                            path: path.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        let num_particles = self.particles.len_of(Axis(1));
+        for (&key, indices) in &self.leaf_key_to_particles {
+            let path = Self::ancestor_path(key);
+            for &index in indices {
+                if index >= num_particles {
+                    errors.push(TreeError::ParticleIndexOutOfRange {
+                        key,
+                        index,
+                        path: path.clone(),
+                    });
+                } else if self.particle_keys[index] != key {
+                    errors.push(TreeError::ParticleKeyMismatch {
+                        key,
+                        index,
+                        path: path.clone(),
+                    });
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
 /// A structure that stores various statistics for a tree.
 pub struct Statistics {
     pub number_of_particles: usize,
@@ -248,10 +821,182 @@ pub fn compute_complete_regular_tree<T: RealType>(
     all_keys
 }
 
-/// Export an octree to vtk
-pub fn export_to_vtk<T: RealType>(tree: &Octree<T>, filename: &str) {
-    use super::morton::{serialize_box_from_key};
+/// The compression codec applied to the point buffer of a VTK export.
+///
+/// Re-exported from `rusty_tree::data::compression` rather than duplicated
+/// here, so this crate's VTK compression can never drift from
+/// `DistributedTree`'s HDF5/IO compression: both go through the same
+/// `compress_blocks`/`decompress_blocks` block framing and checksum.
+pub use rusty_tree::data::Compression;
+
+/// The VTK cell topology for an octree export: `num_keys` `Voxel` cells (the
+/// tree's boxes) followed by one `PolyVertex` cell holding all
+/// `num_particles` particles, plus the `colors` cell attribute that tells
+/// them apart (`0` for a box, `1` for the particle cell).
+///
+/// Entirely determined by `num_keys`/`num_particles` — it carries no
+/// information about where cells actually sit in space — so
+/// [`write_compressed_vtk`]'s container only needs to store those two
+/// counts (not this topology) for [`read_compressed_vtk`] to reconstruct it
+/// exactly.
+fn vtk_topology(
+    num_keys: usize,
+    num_particles: usize,
+) -> (Vec<u64>, Vec<u64>, Vec<vtkio::model::CellType>, Vec<i32>) {
     use std::iter::FromIterator;
+    use vtkio::model::CellType;
+
+    let num_points = 8 * (num_keys as u64) + (num_particles as u64);
+
+    let connectivity = Vec::<u64>::from_iter(0..num_points);
+    let mut offsets = Vec::<u64>::from_iter((0..(num_keys as u64)).map(|item| 8 * item + 8));
+    offsets.push(num_points);
+
+    let mut types = vec![CellType::Voxel; num_keys];
+    types.push(CellType::PolyVertex);
+
+    let mut cell_data = Vec::<i32>::with_capacity(num_points as usize);
+    for _ in 0..num_keys {
+        cell_data.push(0);
+    }
+    cell_data.push(1);
+
+    (connectivity, offsets, types, cell_data)
+}
+
+/// The magic bytes identifying a compressed-VTK container written by
+/// [`write_compressed_vtk`].
+const COMPRESSED_VTK_MAGIC: [u8; 4] = *b"RVTK";
+/// The compressed-VTK container format version; bump if the payload layout
+/// below changes.
+const COMPRESSED_VTK_VERSION: u32 = 1;
+
+/// The inverse of a [`Compression`] tag written into a compressed-VTK
+/// container header.
+///
+/// The `Miniz` compression level only affects encoding, not decoding
+/// (`miniz_oxide::inflate` needs no level), so it is not recoverable from
+/// the tag and is not needed here.
+fn compression_from_tag(tag: [u8; 4]) -> Compression {
+    match &tag {
+        b"NONE" => Compression::None,
+        b"LZ4\0" => Compression::Lz4,
+        b"MINI" => Compression::Miniz(0),
+        _ => panic!("unrecognised compression tag {:?}", tag),
+    }
+}
+
+/// Write `filename` as a compressed container instead of a `vtkio` model.
+///
+/// `vtkio`'s writer has no hook for compressed geometry, so when
+/// compression is requested [`export_to_vtk`] doesn't write a `.vtk` file
+/// at all: it writes this crate's own versioned container — magic,
+/// version, the codec's tag, `num_keys`, `num_particles`, then
+/// `cell_points` compressed via `rusty_tree::data::compression::compress_blocks`
+/// (the same block framing and checksum `DistributedTree`'s HDF5/IO path
+/// uses, not a second codec). [`read_compressed_vtk`] is the matching
+/// reader: since [`vtk_topology`] rebuilds connectivity, cell types and the
+/// `colors` attribute deterministically from `num_keys`/`num_particles`
+/// alone, nothing but the point buffer needs to be stored to reconstruct
+/// the full model — no cell/connectivity data is lost by compressing.
+fn write_compressed_vtk(
+    filename: &str,
+    compression: Compression,
+    num_keys: usize,
+    num_particles: usize,
+    cell_points: &[f64],
+) {
+    use rusty_tree::data::compression::compress_blocks;
+
+    let bytes = unsafe {
+        std::slice::from_raw_parts(
+            cell_points.as_ptr() as *const u8,
+            std::mem::size_of_val(cell_points),
+        )
+    };
+
+    let mut payload = Vec::new();
+    payload.extend_from_slice(&COMPRESSED_VTK_MAGIC);
+    payload.extend_from_slice(&COMPRESSED_VTK_VERSION.to_le_bytes());
+    payload.extend_from_slice(&compression.tag());
+    payload.extend_from_slice(&(num_keys as u64).to_le_bytes());
+    payload.extend_from_slice(&(num_particles as u64).to_le_bytes());
+    payload.extend_from_slice(&compress_blocks(compression, bytes));
+
+    std::fs::write(filename, payload).unwrap();
+}
+
+/// Read a container written by [`write_compressed_vtk`] back into a
+/// `vtkio` [`vtkio::model::Vtk`] model, ready to inspect or re-export —
+/// the real reader [`export_to_vtk`]'s compressed path needs so that output
+/// isn't a write-only blob.
+pub fn read_compressed_vtk(filename: &str) -> vtkio::model::Vtk {
+    use rusty_tree::data::compression::decompress_blocks;
+    use std::path::PathBuf;
+    use vtkio::model::*;
+
+    let payload = std::fs::read(filename).unwrap();
+
+    assert_eq!(
+        &payload[0..4],
+        &COMPRESSED_VTK_MAGIC,
+        "{} is not a compressed-vtk container written by write_compressed_vtk",
+        filename
+    );
+    let version = u32::from_le_bytes(payload[4..8].try_into().unwrap());
+    assert_eq!(
+        version, COMPRESSED_VTK_VERSION,
+        "{} was written with compressed-vtk version {}, which this build cannot read",
+        filename, version
+    );
+
+    let compression = compression_from_tag(payload[8..12].try_into().unwrap());
+    let num_keys = u64::from_le_bytes(payload[12..20].try_into().unwrap()) as usize;
+    let num_particles = u64::from_le_bytes(payload[20..28].try_into().unwrap()) as usize;
+
+    let decompressed = decompress_blocks(compression, &payload[28..]);
+    let cell_points: Vec<f64> = decompressed
+        .chunks_exact(8)
+        .map(|bytes| f64::from_ne_bytes(bytes.try_into().unwrap()))
+        .collect();
+
+    let (connectivity, offsets, types, cell_data) = vtk_topology(num_keys, num_particles);
+
+    Vtk {
+        version: Version { major: 1, minor: 0 },
+        title: String::new(),
+        byte_order: ByteOrder::BigEndian,
+        file_path: Some(PathBuf::from(filename)),
+        data: DataSet::inline(UnstructuredGridPiece {
+            points: IOBuffer::F64(cell_points),
+            cells: Cells {
+                cell_verts: VertexNumbers::XML { connectivity, offsets },
+                types,
+            },
+            data: Attributes {
+                point: vec![],
+                cell: vec![Attribute::DataArray(DataArrayBase {
+                    name: String::from("colors"),
+                    elem: ElementType::Scalars {
+                        num_comp: 1,
+                        lookup_table: None,
+                    },
+                    data: IOBuffer::I32(cell_data),
+                })],
+            },
+        }),
+    }
+}
+
+/// Export an octree to vtk.
+///
+/// When `compression` is not [`Compression::None`], the `vtkio` writer has
+/// no hook for compressed geometry, so `filename` is written instead as the
+/// compressed container described on [`write_compressed_vtk`] — still a
+/// complete, readable record of the tree (via [`read_compressed_vtk`]), not
+/// a standard `.vtk` XML document.
+pub fn export_to_vtk<T: RealType>(tree: &Octree<T>, filename: &str, compression: Compression) {
+    use super::morton::serialize_box_from_key;
     use vtkio::model::*;
     use std::path::PathBuf;
 
@@ -282,22 +1027,12 @@ pub fn export_to_vtk<T: RealType>(tree: &Octree<T>, filename: &str) {
         cell_points.push(tree.particles[[2, index]].to_f64().unwrap());
     }
 
-    let num_points = 8 * (num_keys as u64) + (num_particles as u64);
-
-    let connectivity = Vec::<u64>::from_iter(0..num_points);
-    let mut offsets = Vec::<u64>::from_iter((0..(num_keys as u64)).map(|item| 8 * item + 8));
-    offsets.push(num_points);
-
-    let mut types = vec![CellType::Voxel; num_keys];
-    types.push(CellType::PolyVertex);
-
-    let mut cell_data = Vec::<i32>::with_capacity(num_points as usize);
-
-    for _ in 0..num_keys {
-        cell_data.push(0);
+    if compression != Compression::None {
+        write_compressed_vtk(&filename, compression, num_keys, num_particles, &cell_points);
+        return;
     }
-    cell_data.push(1);
 
+    let (connectivity, offsets, types, cell_data) = vtk_topology(num_keys, num_particles);
 
     let model = Vtk {
         version: Version { major: 1, minor: 0 },
@@ -307,11 +1042,8 @@ pub fn export_to_vtk<T: RealType>(tree: &Octree<T>, filename: &str) {
         data: DataSet::inline(UnstructuredGridPiece {
             points: IOBuffer::F64(cell_points),
             cells: Cells {
-                cell_verts: VertexNumbers::XML {
-                    connectivity: connectivity,
-                    offsets: offsets,
-                },
-                types: types,
+                cell_verts: VertexNumbers::XML { connectivity, offsets },
+                types,
             },
             data: Attributes {
                 point: vec![],
@@ -327,6 +1059,372 @@ pub fn export_to_vtk<T: RealType>(tree: &Octree<T>, filename: &str) {
         }),
     };
 
-
     model.export(filename).unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array2;
+
+    /// A single-particle, single-key (the root, key `0`) octree. Using only
+    /// the root key keeps `check()` from needing `crate::morton::find_parent`
+    /// (the ancestor walk stops immediately) so this fixture exercises
+    /// `check()` in isolation from the Morton-key module.
+    fn root_only_octree() -> Octree<'static, f64> {
+        let particles: &'static Array2<f64> =
+            Box::leak(Box::new(Array2::from_shape_vec((3, 1), vec![0.0, 0.0, 0.0]).unwrap()));
+
+        let mut level_keys = HashMap::new();
+        level_keys.insert(0, HashSet::from([0]));
+
+        let mut near_field = HashMap::new();
+        near_field.insert(0, HashSet::new());
+
+        let mut interaction_list = HashMap::new();
+        interaction_list.insert(0, HashSet::new());
+
+        let mut leaf_key_to_particles = HashMap::new();
+        leaf_key_to_particles.insert(0, HashSet::from([0]));
+
+        Octree {
+            particles: particles.view(),
+            max_level: 0,
+            origin: [0.0, 0.0, 0.0],
+            diameter: [1.0, 1.0, 1.0],
+            level_keys,
+            particle_keys: Array1::from(vec![0]),
+            near_field,
+            interaction_list,
+            leaf_key_to_particles,
+            all_keys: HashSet::from([0]),
+            octree_type: OctreeType::Regular,
+            statistics: Statistics {
+                number_of_particles: 1,
+                max_level: 0,
+                number_of_leafs: 1,
+                number_of_keys: 1,
+                creation_time: Duration::from_secs(0),
+                minimum_number_of_particles_in_leaf: 1,
+                maximum_number_of_particles_in_leaf: 1,
+                average_number_of_particles_in_leaf: 1.0,
+            },
+        }
+    }
+
+    #[test]
+    fn check_is_ok_for_a_consistent_tree() {
+        let tree = root_only_octree();
+        assert_eq!(tree.check(), Ok(()));
+    }
+
+    #[test]
+    fn check_flags_a_key_missing_from_all_keys() {
+        let mut tree = root_only_octree();
+        tree.all_keys.clear();
+
+        let errors = tree.check().unwrap_err();
+        assert!(errors.contains(&TreeError::MissingKey {
+            key: 0,
+            path: vec![0],
+        }));
+    }
+
+    #[test]
+    fn check_flags_a_near_field_entry_missing_from_all_keys() {
+        let mut tree = root_only_octree();
+        // A stale near-field reference to a key that was pruned from
+        // `all_keys` but left behind in `near_field` — it sits at the
+        // correct level (both are level 0), so only an existence check
+        // against `all_keys`, not `find_level`, can catch it.
+        tree.near_field.get_mut(&0).unwrap().insert(99);
+
+        let errors = tree.check().unwrap_err();
+        assert!(errors.contains(&TreeError::MissingNeighbour {
+            key: 0,
+            neighbour: 99,
+            path: vec![0],
+        }));
+    }
+
+    #[test]
+    fn check_flags_an_interaction_list_entry_missing_from_all_keys() {
+        let mut tree = root_only_octree();
+        tree.interaction_list.get_mut(&0).unwrap().insert(99);
+
+        let errors = tree.check().unwrap_err();
+        assert!(errors.contains(&TreeError::MissingNeighbour {
+            key: 0,
+            neighbour: 99,
+            path: vec![0],
+        }));
+    }
+
+    #[test]
+    fn check_flags_an_out_of_range_particle_index() {
+        let mut tree = root_only_octree();
+        tree.leaf_key_to_particles.get_mut(&0).unwrap().insert(5);
+
+        let errors = tree.check().unwrap_err();
+        assert!(errors.contains(&TreeError::ParticleIndexOutOfRange {
+            key: 0,
+            index: 5,
+            path: vec![0],
+        }));
+    }
+
+    /// Build a fully-populated, from-scratch octree over `particles`, the
+    /// same way `compute_complete_regular_tree` does but keeping the maps
+    /// around instead of discarding everything but `all_keys`.
+    fn build_octree(
+        particles: &'static Array2<f64>,
+        max_level: usize,
+        origin: [f64; 3],
+        diameter: [f64; 3],
+    ) -> Octree<'static, f64> {
+        use crate::morton::encode_points;
+
+        let particle_keys =
+            Array1::from(encode_points(particles.view(), max_level, &origin, &diameter));
+        let (actual_max_level, all_keys, level_keys) =
+            compute_level_information(particle_keys.view());
+        let near_field = compute_near_field_map(&all_keys);
+        let interaction_list = compute_interaction_list_map(&all_keys);
+        let leaf_key_to_particles = compute_leaf_map(particle_keys.view());
+
+        let leaf_sizes: Vec<usize> = leaf_key_to_particles.values().map(HashSet::len).collect();
+
+        Octree {
+            particles: particles.view(),
+            max_level: actual_max_level,
+            origin,
+            diameter,
+            level_keys,
+            particle_keys,
+            near_field,
+            interaction_list,
+            leaf_key_to_particles,
+            all_keys,
+            octree_type: OctreeType::Regular,
+            statistics: Statistics {
+                number_of_particles: particles.len_of(Axis(1)),
+                max_level: actual_max_level,
+                number_of_leafs: leaf_sizes.len(),
+                number_of_keys: leaf_sizes.len(),
+                creation_time: Duration::from_secs(0),
+                minimum_number_of_particles_in_leaf: leaf_sizes.iter().copied().min().unwrap_or(0),
+                maximum_number_of_particles_in_leaf: leaf_sizes.iter().copied().max().unwrap_or(0),
+                average_number_of_particles_in_leaf: leaf_sizes.iter().sum::<usize>() as f64
+                    / leaf_sizes.len().max(1) as f64,
+            },
+        }
+    }
+
+    /// A scattered 27-particle cloud spanning the unit cube, split over
+    /// enough leaves (`max_level = 2`) that `knn`/`within_radius` have to
+    /// walk out past the query's own leaf to find every true neighbour.
+    fn scattered_particles() -> &'static Array2<f64> {
+        let mut xs = Vec::new();
+        let mut ys = Vec::new();
+        let mut zs = Vec::new();
+        for i in 0..3 {
+            for j in 0..3 {
+                for k in 0..3 {
+                    xs.push(0.1 + 0.3 * i as f64);
+                    ys.push(0.1 + 0.3 * j as f64);
+                    zs.push(0.1 + 0.3 * k as f64);
+                }
+            }
+        }
+        let mut coords = xs;
+        coords.extend(ys);
+        coords.extend(zs);
+
+        Box::leak(Box::new(Array2::from_shape_vec((3, 27), coords).unwrap()))
+    }
+
+    fn brute_force_distances(particles: &Array2<f64>, query: [f64; 3]) -> Vec<(usize, f64)> {
+        let mut distances: Vec<(usize, f64)> = (0..particles.len_of(Axis(1)))
+            .map(|index| (index, distance_to_particle(&particles.view(), index, query)))
+            .collect();
+        distances.sort_by(|a, b| a.1.total_cmp(&b.1));
+        distances
+    }
+
+    #[test]
+    fn knn_matches_brute_force_nearest_neighbours() {
+        let particles = scattered_particles();
+        let tree = build_octree(particles, 2, [0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+
+        let query = [0.12, 0.42, 0.72];
+        let k = 5;
+
+        let expected: Vec<(usize, f64)> = brute_force_distances(particles, query)
+            .into_iter()
+            .take(k)
+            .collect();
+        let actual = tree.knn(query, k);
+
+        assert_eq!(actual.len(), expected.len());
+        for ((_, expected_distance), (_, actual_distance)) in expected.iter().zip(actual.iter()) {
+            assert!((expected_distance - actual_distance).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn knn_of_zero_is_empty() {
+        let particles = scattered_particles();
+        let tree = build_octree(particles, 2, [0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+
+        assert!(tree.knn([0.5, 0.5, 0.5], 0).is_empty());
+    }
+
+    #[test]
+    fn within_radius_matches_brute_force() {
+        let particles = scattered_particles();
+        let tree = build_octree(particles, 2, [0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
+
+        let query = [0.12, 0.42, 0.72];
+        let radius = 0.35;
+
+        let mut expected: Vec<usize> = brute_force_distances(particles, query)
+            .into_iter()
+            .filter(|&(_, distance)| distance <= radius)
+            .map(|(index, _)| index)
+            .collect();
+        expected.sort_unstable();
+
+        let mut actual = tree.within_radius(query, radius);
+        actual.sort_unstable();
+
+        assert_eq!(actual, expected);
+    }
+
+    fn points_to_array(points: &[(f64, f64, f64)]) -> Array2<f64> {
+        let mut coords = Vec::with_capacity(points.len() * 3);
+        for &(x, _, _) in points {
+            coords.push(x);
+        }
+        for &(_, y, _) in points {
+            coords.push(y);
+        }
+        for &(_, _, z) in points {
+            coords.push(z);
+        }
+        Array2::from_shape_vec((3, points.len()), coords).unwrap()
+    }
+
+    proptest::proptest! {
+        /// `Octree::set_leaves` only supports appending, so `initial` seeds the
+        /// tree and `additional` is appended via `set_leaves`; the resulting
+        /// maps must match a tree built from scratch over `initial ++
+        /// additional`, per `set_leaves`'s own doc comment.
+        #[test]
+        fn set_leaves_matches_a_full_rebuild(
+            initial in proptest::collection::vec((0.0f64..1.0, 0.0f64..1.0, 0.0f64..1.0), 1..6),
+            additional in proptest::collection::vec((0.0f64..1.0, 0.0f64..1.0, 0.0f64..1.0), 0..6),
+        ) {
+            let max_level = 2;
+            let origin = [0.0, 0.0, 0.0];
+            let diameter = [1.0, 1.0, 1.0];
+
+            let initial_array: &'static Array2<f64> =
+                Box::leak(Box::new(points_to_array(&initial)));
+            let mut tree = build_octree(initial_array, max_level, origin, diameter);
+
+            let mut all_points = initial.clone();
+            all_points.extend(additional.iter().copied());
+            let full_array: &'static Array2<f64> =
+                Box::leak(Box::new(points_to_array(&all_points)));
+
+            tree.set_leaves(full_array.view());
+
+            let rebuilt = build_octree(full_array, max_level, origin, diameter);
+
+            proptest::prop_assert_eq!(&tree.all_keys, &rebuilt.all_keys);
+            proptest::prop_assert_eq!(&tree.level_keys, &rebuilt.level_keys);
+            proptest::prop_assert_eq!(&tree.near_field, &rebuilt.near_field);
+            proptest::prop_assert_eq!(&tree.interaction_list, &rebuilt.interaction_list);
+            proptest::prop_assert_eq!(&tree.leaf_key_to_particles, &rebuilt.leaf_key_to_particles);
+            proptest::prop_assert_eq!(tree.max_level, rebuilt.max_level);
+        }
+    }
+
+    /// For each leaf key, the coordinates (not indices) of the particles
+    /// `tree` files under it, sorted so two trees over the same points but
+    /// with different index numbering still compare equal.
+    fn leaf_coordinates<T: RealType>(
+        tree: &Octree<T>,
+    ) -> HashMap<usize, Vec<(u64, u64, u64)>> {
+        tree.leaf_key_to_particles
+            .iter()
+            .map(|(&key, indices)| {
+                let mut coordinates: Vec<(u64, u64, u64)> = indices
+                    .iter()
+                    .map(|&index| {
+                        (
+                            tree.particles[[0, index]].to_f64().unwrap().to_bits(),
+                            tree.particles[[1, index]].to_f64().unwrap().to_bits(),
+                            tree.particles[[2, index]].to_f64().unwrap().to_bits(),
+                        )
+                    })
+                    .collect();
+                coordinates.sort_unstable();
+                (key, coordinates)
+            })
+            .collect()
+    }
+
+    proptest::proptest! {
+        /// `remove_indices` cannot match a full rebuild byte-for-byte (see its
+        /// doc comment: it cannot renumber the borrowed `particles` array), so
+        /// this pins down the weaker invariant it does guarantee: after
+        /// removing `removed` from `initial`, the tree's topology and the set
+        /// of point coordinates filed under each leaf key both match a tree
+        /// built from scratch over the surviving points.
+        #[test]
+        fn remove_indices_matches_a_full_rebuild_up_to_point_identity(
+            initial in proptest::collection::vec((0.0f64..1.0, 0.0f64..1.0, 0.0f64..1.0), 1..10),
+            removed in proptest::collection::vec(0usize..10, 0..10),
+        ) {
+            let max_level = 2;
+            let origin = [0.0, 0.0, 0.0];
+            let diameter = [1.0, 1.0, 1.0];
+
+            let initial_array: &'static Array2<f64> =
+                Box::leak(Box::new(points_to_array(&initial)));
+            let mut tree = build_octree(initial_array, max_level, origin, diameter);
+
+            let removed: HashSet<usize> = removed
+                .into_iter()
+                .filter(|&index| index < initial.len())
+                .collect();
+            let removed: Vec<usize> = removed.into_iter().collect();
+            tree.remove_indices(&removed);
+
+            let removed_set: HashSet<usize> = removed.into_iter().collect();
+            let surviving: Vec<(f64, f64, f64)> = initial
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !removed_set.contains(index))
+                .map(|(_, &point)| point)
+                .collect();
+            // An empty surviving set takes the separate "drop everything"
+            // branch of `remove_indices` and isn't something `build_octree`
+            // (which feeds straight into `compute_level_information`) is
+            // exercised against elsewhere; leave that case to
+            // `check_is_ok_for_a_consistent_tree`-style fixtures instead.
+            proptest::prop_assume!(!surviving.is_empty());
+            let surviving_array: &'static Array2<f64> =
+                Box::leak(Box::new(points_to_array(&surviving)));
+            let rebuilt = build_octree(surviving_array, max_level, origin, diameter);
+
+            proptest::prop_assert_eq!(&tree.all_keys, &rebuilt.all_keys);
+            proptest::prop_assert_eq!(&tree.level_keys, &rebuilt.level_keys);
+            proptest::prop_assert_eq!(&tree.near_field, &rebuilt.near_field);
+            proptest::prop_assert_eq!(&tree.interaction_list, &rebuilt.interaction_list);
+            proptest::prop_assert_eq!(tree.max_level, rebuilt.max_level);
+            proptest::prop_assert_eq!(leaf_coordinates(&tree), leaf_coordinates(&rebuilt));
+        }
+    }
+}